@@ -7,6 +7,162 @@ use super::Layout;
 use crate::size::{Size, Size2D};
 use LayoutAction::*;
 
+/// A color with red, green, blue and alpha channels.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Display for Color {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+    }
+}
+
+/// A 2×3 affine transform, mapping a point `(x, y)` to
+/// `(a*x + b*y + tx, c*x + d*y + ty)`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transform {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub tx: Size,
+    pub ty: Size,
+}
+
+impl Transform {
+    /// The identity transform, leaving points unchanged.
+    pub fn identity() -> Transform {
+        Transform {
+            a: 1.0,
+            b: 0.0,
+            c: 0.0,
+            d: 1.0,
+            tx: Size::zero(),
+            ty: Size::zero(),
+        }
+    }
+
+    /// A transform that only translates by `offset`.
+    pub fn translation(offset: Size2D) -> Transform {
+        Transform {
+            a: 1.0,
+            b: 0.0,
+            c: 0.0,
+            d: 1.0,
+            tx: offset.x,
+            ty: offset.y,
+        }
+    }
+
+    /// Compose this transform with `inner`, such that applying the result to
+    /// a point is the same as first applying `inner` and then `self`.
+    pub fn compose(&self, inner: &Transform) -> Transform {
+        Transform {
+            a: self.a * inner.a + self.b * inner.c,
+            b: self.a * inner.b + self.b * inner.d,
+            c: self.c * inner.a + self.d * inner.c,
+            d: self.c * inner.b + self.d * inner.d,
+            tx: Size::pt(self.a * inner.tx.to_pt() + self.b * inner.ty.to_pt()) + self.tx,
+            ty: Size::pt(self.c * inner.tx.to_pt() + self.d * inner.ty.to_pt()) + self.ty,
+        }
+    }
+
+    /// Apply this transform to a point.
+    pub fn apply(&self, point: Size2D) -> Size2D {
+        Size2D {
+            x: Size::pt(self.a * point.x.to_pt() + self.b * point.y.to_pt()) + self.tx,
+            y: Size::pt(self.c * point.x.to_pt() + self.d * point.y.to_pt()) + self.ty,
+        }
+    }
+
+    /// Apply only this transform's linear part (scale, rotation, shear) to a vector,
+    /// ignoring translation. Used for extents that travel alongside a transformed point,
+    /// like a box's size, which should scale but not be offset.
+    pub fn apply_vector(&self, vector: Size2D) -> Size2D {
+        Size2D {
+            x: Size::pt(self.a * vector.x.to_pt() + self.b * vector.y.to_pt()),
+            y: Size::pt(self.c * vector.x.to_pt() + self.d * vector.y.to_pt()),
+        }
+    }
+
+    /// The scale factor this transform applies along its x-axis, i.e. the length of the
+    /// transformed unit x-vector. Used to scale scalar extents, like a line's thickness,
+    /// that have no direction of their own to transform as a vector.
+    pub fn scale_factor(&self) -> f64 {
+        (self.a * self.a + self.c * self.c).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod transform_tests {
+    use super::*;
+
+    fn point(x: f64, y: f64) -> Size2D {
+        Size2D { x: Size::pt(x), y: Size::pt(y) }
+    }
+
+    fn scale(sx: f64, sy: f64) -> Transform {
+        Transform { a: sx, b: 0.0, c: 0.0, d: sy, tx: Size::zero(), ty: Size::zero() }
+    }
+
+    #[test]
+    fn compose_applies_inner_before_outer() {
+        let outer = Transform::translation(point(10.0, 20.0));
+        let inner = scale(2.0, 3.0);
+
+        let combined = outer.compose(&inner);
+        let result = combined.apply(point(3.0, 4.0));
+
+        // Scale first (6, 12), then translate by (10, 20).
+        assert_eq!(result.x.to_pt(), 16.0);
+        assert_eq!(result.y.to_pt(), 32.0);
+    }
+
+    #[test]
+    fn compose_nests_through_two_levels() {
+        let a = Transform::translation(point(1.0, 1.0));
+        let b = scale(2.0, 2.0);
+        let c = Transform::translation(point(5.0, 0.0));
+
+        // Mirrors how `add_layout` composes a parent's accumulated transform with a
+        // child's local translate-plus-transform on every level of nesting.
+        let nested = a.compose(&b).compose(&c);
+        let direct = a.compose(&b.compose(&c));
+
+        let p = point(2.0, 2.0);
+        assert_eq!(nested.apply(p).x.to_pt(), direct.apply(p).x.to_pt());
+        assert_eq!(nested.apply(p).y.to_pt(), direct.apply(p).y.to_pt());
+    }
+
+    #[test]
+    fn apply_vector_scales_but_does_not_translate() {
+        let transform = Transform {
+            a: 2.0,
+            b: 0.0,
+            c: 0.0,
+            d: 3.0,
+            tx: Size::pt(100.0),
+            ty: Size::pt(100.0),
+        };
+
+        let extent = transform.apply_vector(point(5.0, 6.0));
+
+        assert_eq!(extent.x.to_pt(), 10.0);
+        assert_eq!(extent.y.to_pt(), 18.0);
+    }
+
+    #[test]
+    fn scale_factor_reflects_x_axis_scale() {
+        let transform = scale(2.0, 5.0);
+        assert_eq!(transform.scale_factor(), 2.0);
+    }
+}
+
 /// A layouting action.
 #[derive(Clone)]
 pub enum LayoutAction {
@@ -14,8 +170,13 @@ pub enum LayoutAction {
     MoveAbsolute(Size2D),
     /// Set the font by index and font size.
     SetFont(usize, Size),
+    /// Set the fill color for text and debug boxes.
+    SetFill(Color),
     /// Write text starting at the current position.
     WriteText(String),
+    /// Draw a line from one point to another with the given thickness, used
+    /// for underlines, strikethroughs and link rules.
+    DrawLine(Size2D, Size2D, Size),
     /// Visualize a box for debugging purposes.
     /// The arguments are position and size.
     DebugBox(Size2D, Size2D),
@@ -27,7 +188,17 @@ impl LayoutAction {
         match self {
             MoveAbsolute(s) => write!(f, "m {:.4} {:.4}", s.x.to_pt(), s.y.to_pt()),
             SetFont(i, s) => write!(f, "f {} {}", i, s.to_pt()),
+            SetFill(c) => write!(f, "c {} {} {} {}", c.r, c.g, c.b, c.a),
             WriteText(s) => write!(f, "w {}", s),
+            DrawLine(start, end, thickness) => write!(
+                f,
+                "l {:.4} {:.4} {:.4} {:.4} {:.4}",
+                start.x.to_pt(),
+                start.y.to_pt(),
+                end.x.to_pt(),
+                end.y.to_pt(),
+                thickness.to_pt()
+            ),
             DebugBox(p, s) => write!(
                 f,
                 "b {} {} {} {}",
@@ -46,7 +217,11 @@ impl Display for LayoutAction {
         match self {
             MoveAbsolute(s) => write!(f, "move {} {}", s.x, s.y),
             SetFont(i, s) => write!(f, "font {} {}", i, s),
+            SetFill(c) => write!(f, "fill {}", c),
             WriteText(s) => write!(f, "write \"{}\"", s),
+            DrawLine(start, end, thickness) => {
+                write!(f, "line {} {} {}", start, end, thickness)
+            }
             DebugBox(p, s) => write!(f, "box {} {}", p, s),
         }
     }
@@ -61,17 +236,22 @@ debug_display!(LayoutAction);
 /// All configuration actions (like moving, setting fonts, ...) are only flushed when
 /// content is written.
 ///
-/// Furthermore, the action list can translate absolute position into a coordinate system
-/// with a different origin. This is realized in the `add_box` method, which allows a layout to
-/// be added at a position, effectively translating all movement actions inside the layout
-/// by the position.
+/// Furthermore, the action list can transform absolute positions into a coordinate system
+/// with a different origin, scale or rotation. This is realized in the `add_layout` method,
+/// which allows a layout to be added at a position (optionally under an additional affine
+/// transform), effectively transforming all geometric actions inside the layout. Nested
+/// layouts compose their transforms on an internal stack, so a rotated or scaled layout may
+/// itself contain further transformed sub-layouts.
 #[derive(Debug, Clone)]
 pub struct LayoutActionList {
-    pub origin: Size2D,
     actions: Vec<LayoutAction>,
+    transform: Transform,
+    transform_stack: Vec<Transform>,
     active_font: (usize, Size),
+    active_fill: Option<Color>,
     next_pos: Option<Size2D>,
     next_font: Option<(usize, Size)>,
+    next_fill: Option<Color>,
 }
 
 impl LayoutActionList {
@@ -79,26 +259,62 @@ impl LayoutActionList {
     pub fn new() -> LayoutActionList {
         LayoutActionList {
             actions: vec![],
-            origin: Size2D::zero(),
+            transform: Transform::identity(),
+            transform_stack: vec![],
             active_font: (std::usize::MAX, Size::zero()),
+            active_fill: None,
             next_pos: None,
             next_font: None,
+            next_fill: None,
         }
     }
 
     /// Add an action to the list.
     pub fn add(&mut self, action: LayoutAction) {
         match action {
-            MoveAbsolute(pos) => self.next_pos = Some(self.origin + pos),
-            DebugBox(pos, size) => self.actions.push(DebugBox(self.origin + pos, size)),
+            MoveAbsolute(pos) => self.next_pos = Some(self.transform.apply(pos)),
+            DebugBox(pos, size) => {
+                self.flush_fill();
+                self.actions.push(DebugBox(
+                    self.transform.apply(pos),
+                    self.transform.apply_vector(size),
+                ));
+            }
+            DrawLine(start, end, thickness) => self.actions.push(DrawLine(
+                self.transform.apply(start),
+                self.transform.apply(end),
+                Size::pt(thickness.to_pt() * self.transform.scale_factor()),
+            )),
 
             SetFont(index, size) => {
                 self.next_font = Some((index, size));
             }
+            SetFill(color) => {
+                self.next_fill = Some(color);
+            }
+
+            WriteText(text) => {
+                let mergeable =
+                    self.next_pos.is_none() && self.next_font.is_none() && self.next_fill.is_none();
+
+                if mergeable {
+                    if let Some(WriteText(last)) = self.actions.last_mut() {
+                        last.push_str(&text);
+                        return;
+                    }
+                }
+
+                self.flush_position();
+                self.flush_font();
+                self.flush_fill();
+
+                self.actions.push(WriteText(text));
+            }
 
             _ => {
                 self.flush_position();
                 self.flush_font();
+                self.flush_fill();
 
                 self.actions.push(action);
             }
@@ -113,19 +329,32 @@ impl LayoutActionList {
         }
     }
 
-    /// Add a layout at a position. All move actions inside the layout are translated
-    /// by the position.
-    pub fn add_layout(&mut self, position: Size2D, layout: Layout) {
+    /// Add a layout at a position, optionally under an additional affine transform (for
+    /// rotation or scaling). All geometric actions inside the layout are transformed
+    /// accordingly. Equivalent to a translate-only transform if `transform` is `None`.
+    pub fn add_layout(&mut self, position: Size2D, transform: Option<Transform>, layout: Layout) {
         self.flush_position();
 
-        self.origin = position;
-        self.next_pos = Some(position);
+        let local = match transform {
+            Some(transform) => Transform::translation(position).compose(&transform),
+            None => Transform::translation(position),
+        };
+
+        self.transform_stack.push(self.transform);
+        self.transform = self.transform.compose(&local);
+
+        let origin = self.transform.apply(Size2D::zero());
+        self.next_pos = Some(origin);
 
         if layout.debug_render {
-            self.actions.push(DebugBox(position, layout.dimensions));
+            self.flush_fill();
+            self.actions
+                .push(DebugBox(origin, self.transform.apply_vector(layout.dimensions)));
         }
 
         self.extend(layout.actions);
+
+        self.transform = self.transform_stack.pop().expect("transform stack underflow");
     }
 
     /// Whether there are any actions in this list.
@@ -154,4 +383,288 @@ impl LayoutActionList {
             }
         }
     }
+
+    /// Append a cached fill-color action if one is cached.
+    fn flush_fill(&mut self) {
+        if let Some(color) = self.next_fill.take() {
+            if Some(color) != self.active_fill {
+                self.actions.push(SetFill(color));
+                self.active_fill = Some(color);
+            }
+        }
+    }
+
+    /// Drive `backend` through this list's actions, in order.
+    pub fn render<B: Backend>(&self, backend: &mut B) -> io::Result<()> {
+        for action in &self.actions {
+            match action {
+                MoveAbsolute(pos) => backend.move_to(*pos),
+                SetFont(index, size) => backend.set_font(*index, *size),
+                SetFill(color) => backend.set_fill(*color),
+                WriteText(text) => backend.write_text(text),
+                DrawLine(start, end, thickness) => backend.draw_line(*start, *end, *thickness),
+                DebugBox(pos, size) => backend.draw_box(*pos, *size),
+            }?;
+        }
+        Ok(())
+    }
+}
+
+/// A rendering backend that consumes a `LayoutActionList` via `LayoutActionList::render`.
+///
+/// Implementing this trait gives a renderer a typed hook for every action instead of
+/// re-parsing the stringified form produced by `LayoutAction::serialize`. `DebugBackend`
+/// is the reference implementation; a byte-oriented draw-command emitter can be added as
+/// a second implementor without touching how actions are built or optimized.
+///
+/// Every method returns `io::Result<()>` so a failing write (a closed pipe, a full disk)
+/// propagates to the caller of `render` instead of panicking, matching the fallibility of
+/// `LayoutAction::serialize`, which this trait supersedes as the rendering entry point.
+pub trait Backend {
+    /// Move the current position to an absolute point.
+    fn move_to(&mut self, pos: Size2D) -> io::Result<()>;
+    /// Set the active font by index and size.
+    fn set_font(&mut self, index: usize, size: Size) -> io::Result<()>;
+    /// Set the active fill color.
+    fn set_fill(&mut self, color: Color) -> io::Result<()>;
+    /// Write text at the current position.
+    fn write_text(&mut self, text: &str) -> io::Result<()>;
+    /// Draw a line between two points with the given thickness.
+    fn draw_line(&mut self, start: Size2D, end: Size2D, thickness: Size) -> io::Result<()>;
+    /// Draw a debug box at a position with a size.
+    fn draw_box(&mut self, pos: Size2D, size: Size2D) -> io::Result<()>;
+}
+
+/// A `Backend` that reproduces the existing serialized action-stream format.
+pub struct DebugBackend<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> DebugBackend<W> {
+    /// Create a new debug backend writing to `writer`.
+    pub fn new(writer: W) -> DebugBackend<W> {
+        DebugBackend { writer }
+    }
+
+    fn write_action(&mut self, action: &LayoutAction) -> io::Result<()> {
+        action.serialize(&mut self.writer)?;
+        writeln!(self.writer)
+    }
+}
+
+impl<W: Write> Backend for DebugBackend<W> {
+    fn move_to(&mut self, pos: Size2D) -> io::Result<()> {
+        self.write_action(&MoveAbsolute(pos))
+    }
+
+    fn set_font(&mut self, index: usize, size: Size) -> io::Result<()> {
+        self.write_action(&SetFont(index, size))
+    }
+
+    fn set_fill(&mut self, color: Color) -> io::Result<()> {
+        self.write_action(&SetFill(color))
+    }
+
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        self.write_action(&WriteText(text.to_string()))
+    }
+
+    fn draw_line(&mut self, start: Size2D, end: Size2D, thickness: Size) -> io::Result<()> {
+        self.write_action(&DrawLine(start, end, thickness))
+    }
+
+    fn draw_box(&mut self, pos: Size2D, size: Size2D) -> io::Result<()> {
+        self.write_action(&DebugBox(pos, size))
+    }
+}
+
+/// Controls how content is allowed to break across pages.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PageBreaking {
+    /// Only break at an explicit call to `PageBreaker::break_page`. Content that does not
+    /// fit is left to overflow the page.
+    Hard,
+    /// Break automatically as soon as a layout would cross the page boundary. The
+    /// overflowing layout is rolled over atomically onto the next page; it is never split
+    /// mid-box, so a layout taller than `page_height` will not fit on any page.
+    Greedy,
+}
+
+/// Drives a sequence of layouts into pages, splitting the action stream into one
+/// `LayoutActionList` per page once content would cross the configured page height.
+///
+/// In `Greedy` mode, a layout added via `add_layout` that would cross the page boundary
+/// causes the current page to be finalized and the layout to be re-emitted at the top of
+/// a fresh page, whose action list naturally starts with no active font, so font state is
+/// re-established on the new page. The caller keeps laying out content in one flowing,
+/// un-broken coordinate space; the breaker tracks the running offset between that space
+/// and the current page's local `y` and applies it to every incoming position, so a break
+/// — automatic or via an explicit `break_page` call in `Hard` mode — does not desynchronize
+/// the caller's later positions from where content actually landed.
+#[derive(Debug, Clone)]
+pub struct PageBreaker {
+    page_height: Size,
+    breaking: PageBreaking,
+    pages: Vec<Vec<LayoutAction>>,
+    current: LayoutActionList,
+    /// Offset from the caller's flowing `y` coordinate to the current page's local `y`.
+    offset: Size,
+    /// Set after a break; the offset is stale until the next `add_layout` call resyncs it
+    /// so that call's position lands at the top of the new page.
+    needs_resync: bool,
+}
+
+impl PageBreaker {
+    /// Create a new page breaker for pages of the given height.
+    pub fn new(page_height: Size, breaking: PageBreaking) -> PageBreaker {
+        PageBreaker {
+            page_height,
+            breaking,
+            pages: vec![],
+            current: LayoutActionList::new(),
+            offset: Size::zero(),
+            needs_resync: false,
+        }
+    }
+
+    /// Add a layout at a position in the caller's flowing coordinate space, breaking to a
+    /// new page first if it would overflow the current one and the breaker is in `Greedy`
+    /// mode.
+    pub fn add_layout(&mut self, position: Size2D, transform: Option<Transform>, layout: Layout) {
+        if self.needs_resync {
+            self.offset = Size::pt(-position.y.to_pt());
+            self.needs_resync = false;
+        }
+
+        let local = Size2D { x: position.x, y: position.y + self.offset };
+        let extent_y = match &transform {
+            Some(transform) => transform.apply_vector(layout.dimensions).y,
+            None => layout.dimensions.y,
+        };
+
+        let local = if self.breaking == PageBreaking::Greedy && self.overflows(local, extent_y) {
+            self.break_page();
+            self.offset = Size::pt(-position.y.to_pt());
+            self.needs_resync = false;
+            Size2D { x: position.x, y: Size::zero() }
+        } else {
+            local
+        };
+
+        self.current.add_layout(local, transform, layout);
+    }
+
+    /// Whether placing a layout of vertical extent `extent_y` at page-local `position`
+    /// would cross the page boundary.
+    fn overflows(&self, position: Size2D, extent_y: Size) -> bool {
+        position.y + extent_y > self.page_height
+    }
+
+    /// Finalize the current page and start a fresh one. The offset is resynced lazily on
+    /// the next `add_layout` call, whatever position it is placed at becomes the new
+    /// page's top.
+    pub fn break_page(&mut self) {
+        let finished = std::mem::replace(&mut self.current, LayoutActionList::new());
+        self.pages.push(finished.into_vec());
+        self.needs_resync = true;
+    }
+
+    /// Finish paging, returning the finalized action lists, one per page.
+    pub fn finish(mut self) -> Vec<Vec<LayoutAction>> {
+        if !self.current.is_empty() {
+            self.pages.push(self.current.into_vec());
+        }
+        self.pages
+    }
+}
+
+#[cfg(test)]
+mod page_breaker_tests {
+    use super::*;
+
+    fn layout_of_height(height: f64) -> Layout {
+        Layout {
+            debug_render: false,
+            dimensions: Size2D { x: Size::zero(), y: Size::pt(height) },
+            actions: vec![WriteText("x".into())],
+        }
+    }
+
+    fn first_move_y(page: &[LayoutAction]) -> Option<f64> {
+        page.iter().find_map(|action| match action {
+            MoveAbsolute(pos) => Some(pos.y.to_pt()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn resyncs_offset_after_automatic_break() {
+        let mut breaker = PageBreaker::new(Size::pt(500.0), PageBreaking::Greedy);
+
+        breaker.add_layout(
+            Size2D { x: Size::zero(), y: Size::zero() },
+            None,
+            layout_of_height(400.0),
+        );
+        // Flowing y=400 plus a 400pt-tall layout would overflow the 500pt page, so this
+        // should roll over onto a fresh page starting at page-local y=0.
+        breaker.add_layout(
+            Size2D { x: Size::zero(), y: Size::pt(400.0) },
+            None,
+            layout_of_height(400.0),
+        );
+
+        let pages = breaker.finish();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(first_move_y(&pages[1]), Some(0.0));
+    }
+
+    #[test]
+    fn resyncs_offset_after_explicit_break() {
+        let mut breaker = PageBreaker::new(Size::pt(500.0), PageBreaking::Hard);
+
+        breaker.add_layout(
+            Size2D { x: Size::zero(), y: Size::zero() },
+            None,
+            layout_of_height(100.0),
+        );
+        breaker.break_page();
+        // Continues the caller's flowing coordinate space right where the prior layout
+        // ended; page-local, this must land at y=0, not the 100pt gap a stale offset
+        // would leave.
+        breaker.add_layout(
+            Size2D { x: Size::zero(), y: Size::pt(100.0) },
+            None,
+            layout_of_height(100.0),
+        );
+
+        let pages = breaker.finish();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(first_move_y(&pages[1]), Some(0.0));
+    }
+
+    #[test]
+    fn overflow_check_accounts_for_scaling_transform() {
+        let mut breaker = PageBreaker::new(Size::pt(500.0), PageBreaking::Greedy);
+        let scale_up =
+            Transform { a: 1.0, b: 0.0, c: 0.0, d: 2.0, tx: Size::zero(), ty: Size::zero() };
+
+        breaker.add_layout(
+            Size2D { x: Size::zero(), y: Size::zero() },
+            None,
+            layout_of_height(100.0),
+        );
+        // A 300pt-tall layout scaled 2x vertically occupies 600pt starting at y=100,
+        // which overflows the 500pt page even though its raw, untransformed dimensions
+        // would fit fine.
+        breaker.add_layout(
+            Size2D { x: Size::zero(), y: Size::pt(100.0) },
+            Some(scale_up),
+            layout_of_height(300.0),
+        );
+
+        let pages = breaker.finish();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(first_move_y(&pages[1]), Some(0.0));
+    }
 }
\ No newline at end of file